@@ -0,0 +1,449 @@
+//! A delay-based bandwidth estimator driven by transport-wide congestion
+//! control (TWCC) feedback, modeled on
+//! `draft-holmer-rmcat-transport-wide-cc-extensions-01`. Each RTP session
+//! of `webrtcbin`'s internal `rtpbin` emits `on-feedback-rtcp` for every
+//! RTCP feedback packet it receives; [`EncoderBitrateController`] decodes
+//! the TWCC reports among those, tracks a smoothed delay gradient per peer,
+//! and pushes the resulting target bitrate onto the shared video/audio
+//! encoder of every codec branch.
+
+use codecs::Codec;
+use gst;
+use gst::prelude::*;
+use gst_rtp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// RTP header extension URI for transport-wide congestion control, added to
+/// the video/audio payloaders so the remote side knows to send TWCC
+/// feedback back to us.
+pub const TWCC_EXTENSION_URI: &str =
+    "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+
+/// RTCP packet type for a transport-layer feedback (RTPFB) packet.
+const RTCP_RTPFB_TYPE: u32 = 205;
+
+/// FMT value identifying a transport-wide-cc report within an RTPFB packet.
+const TWCC_FMT: u32 = 15;
+
+const GROWTH_FACTOR_PER_SEC: f64 = 1.08;
+const BACKOFF_FACTOR: f64 = 0.85;
+const OVERUSE_HOLD_SECS: f64 = 0.010;
+const MIN_ADAPTIVE_THRESHOLD_SECS: f64 = 0.002;
+
+/// The portion of the total estimate handed to the audio encoder; the rest
+/// goes to video. Audio is also clamped to its own sane range below.
+const AUDIO_BITRATE_SHARE: f64 = 0.08;
+const AUDIO_MIN_BITRATE: i32 = 6_000;
+const AUDIO_MAX_BITRATE: i32 = 64_000;
+
+/// One arrival-feedback burst: a single TWCC report, reduced to the total
+/// size of the packets it covers and the send/arrival time of the group as
+/// a whole. `send_time_secs` and `arrival_time_secs` come from independent
+/// clocks (ours and the peer's TWCC reference clock); only their
+/// consecutive differences within each domain are meaningful.
+#[derive(Clone, Copy)]
+struct PacketGroup {
+    send_time_secs: f64,
+    arrival_time_secs: f64,
+    size_bits: u32,
+}
+
+/// Tracks the smoothed one-way delay gradient for a single peer and derives
+/// a target bitrate from it.
+struct BandwidthEstimator {
+    last_group: Option<PacketGroup>,
+    smoothed_gradient: f64,
+    trend: f64,
+    adaptive_threshold: f64,
+    overuse_since: Option<f64>,
+    // `None` until the first group arrives, since `arrival_time_secs` lives
+    // in the peer's TWCC reference-clock domain (can already be thousands
+    // of seconds by then) -- seeding this at 0.0 would make the very first
+    // `elapsed` enormous and instantly saturate `current_bitrate` to
+    // `u32::MAX` via the growth branch below.
+    last_update_secs: Option<f64>,
+    received_bytes_since_update: u64,
+    current_bitrate: u32,
+}
+
+impl BandwidthEstimator {
+    fn new(start_bitrate: u32) -> Self {
+        BandwidthEstimator {
+            last_group: None,
+            smoothed_gradient: 0.0,
+            trend: 0.0,
+            adaptive_threshold: MIN_ADAPTIVE_THRESHOLD_SECS,
+            overuse_since: None,
+            last_update_secs: None,
+            received_bytes_since_update: 0,
+            current_bitrate: start_bitrate,
+        }
+    }
+
+    /// Feeds in one packet group and returns the estimator's (possibly
+    /// unchanged) unclamped target bitrate.
+    fn on_packet_group(&mut self, group: PacketGroup) -> u32 {
+        self.received_bytes_since_update += u64::from(group.size_bits) / 8;
+
+        if let Some(last) = self.last_group {
+            let send_delta = group.send_time_secs - last.send_time_secs;
+            let arrival_delta = group.arrival_time_secs - last.arrival_time_secs;
+            let gradient = arrival_delta - send_delta;
+
+            const GRADIENT_ALPHA: f64 = 0.25;
+            self.smoothed_gradient =
+                GRADIENT_ALPHA * gradient + (1.0 - GRADIENT_ALPHA) * self.smoothed_gradient;
+
+            const TREND_ALPHA: f64 = 0.1;
+            self.trend = TREND_ALPHA * self.smoothed_gradient + (1.0 - TREND_ALPHA) * self.trend;
+
+            const THRESHOLD_GAIN: f64 = 0.01;
+            self.adaptive_threshold = (self.adaptive_threshold
+                + THRESHOLD_GAIN * (self.trend.abs() - self.adaptive_threshold))
+                .max(MIN_ADAPTIVE_THRESHOLD_SECS);
+
+            if self.trend > self.adaptive_threshold {
+                if self.overuse_since.is_none() {
+                    self.overuse_since = Some(group.arrival_time_secs);
+                }
+            } else {
+                self.overuse_since = None;
+            }
+        }
+        self.last_group = Some(group);
+
+        let now = group.arrival_time_secs;
+        let elapsed = now - *self.last_update_secs.get_or_insert(now);
+        let overusing = self
+            .overuse_since
+            .map_or(false, |since| now - since > OVERUSE_HOLD_SECS);
+
+        if overusing {
+            let received_rate_bps = if elapsed > 0.0 {
+                (self.received_bytes_since_update as f64 * 8.0) / elapsed
+            } else {
+                f64::from(self.current_bitrate)
+            };
+            self.current_bitrate = (BACKOFF_FACTOR * received_rate_bps) as u32;
+            self.overuse_since = None;
+            self.last_update_secs = Some(now);
+            self.received_bytes_since_update = 0;
+        } else if self.trend < self.adaptive_threshold && elapsed >= 1.0 {
+            self.current_bitrate =
+                (f64::from(self.current_bitrate) * GROWTH_FACTOR_PER_SEC.powf(elapsed)) as u32;
+            self.last_update_secs = Some(now);
+            self.received_bytes_since_update = 0;
+        }
+
+        self.current_bitrate
+    }
+}
+
+/// Decodes a TWCC feedback packet's FCI (the bytes following the common
+/// RTPFB header) into a single packet group covering every received packet
+/// the report lists (empty if none of them are found in `send_times`): its
+/// size is the sum of those packets' sizes and its send/arrival time is that
+/// of the last of them, looked up in `send_times`. `send_times`
+/// is shared read-only across every peer watching this branch (the
+/// payloader that stamps it sits upstream of the per-peer tee, so all peers
+/// report feedback against the same sequence space) -- entries are looked
+/// up, never consumed, so one peer's report doesn't blind the next peer's.
+fn decode_twcc_groups(fci: &[u8], send_times: &HashMap<u16, (f64, u32)>) -> Vec<PacketGroup> {
+    if fci.len() < 8 {
+        return Vec::new();
+    }
+    let base_seq = (u16::from(fci[0]) << 8) | u16::from(fci[1]);
+    let status_count = ((u16::from(fci[2]) << 8) | u16::from(fci[3])) as usize;
+    let reference_time =
+        (u32::from(fci[4]) << 16) | (u32::from(fci[5]) << 8) | u32::from(fci[6]);
+    // fci[7] is the feedback packet count, which we don't need.
+
+    let mut statuses = Vec::with_capacity(status_count);
+    let mut offset = 8;
+    while statuses.len() < status_count && offset + 1 < fci.len() {
+        let chunk = (u16::from(fci[offset]) << 8) | u16::from(fci[offset + 1]);
+        offset += 2;
+        if chunk & 0x8000 == 0 {
+            // Run-length chunk: 2-bit symbol, 13-bit run length.
+            let symbol = ((chunk >> 13) & 0x3) as u8;
+            let run_length = chunk & 0x1FFF;
+            for _ in 0..run_length {
+                statuses.push(symbol);
+            }
+        } else if chunk & 0x4000 == 0 {
+            // Status vector chunk of 14 one-bit symbols.
+            for i in 0..14 {
+                statuses.push(((chunk >> (13 - i)) & 0x1) as u8);
+            }
+        } else {
+            // Status vector chunk of 7 two-bit symbols.
+            for i in 0..7 {
+                statuses.push(((chunk >> (12 - i * 2)) & 0x3) as u8);
+            }
+        }
+    }
+    statuses.truncate(status_count);
+
+    // The reference time is in 64ms units; it's the clock origin that each
+    // packet's receive delta (250us units) accumulates forward from.
+    let mut running_time_secs = f64::from(reference_time) * 0.064;
+    let mut group: Option<PacketGroup> = None;
+
+    for (i, status) in statuses.iter().enumerate() {
+        let delta_250us = match status {
+            1 => {
+                if offset >= fci.len() {
+                    break;
+                }
+                // Small delta is unsigned 8-bit (0..=63.75ms in 250us
+                // units); only the 2-byte status carries a signed delta.
+                let delta = i32::from(fci[offset]);
+                offset += 1;
+                Some(delta)
+            }
+            2 => {
+                if offset + 1 >= fci.len() {
+                    break;
+                }
+                let delta = i32::from(((u16::from(fci[offset]) << 8)
+                    | u16::from(fci[offset + 1])) as i16);
+                offset += 2;
+                Some(delta)
+            }
+            // 0 = not received, 3 = reserved; neither carries a delta.
+            _ => None,
+        };
+
+        if let Some(delta_250us) = delta_250us {
+            running_time_secs += f64::from(delta_250us) * 0.00025;
+            let seq = base_seq.wrapping_add(i as u16);
+            if let Some(&(send_time_secs, size_bits)) = send_times.get(&seq) {
+                let current = group.get_or_insert(PacketGroup {
+                    send_time_secs,
+                    arrival_time_secs: running_time_secs,
+                    size_bits: 0,
+                });
+                current.send_time_secs = send_time_secs;
+                current.arrival_time_secs = running_time_secs;
+                current.size_bits += size_bits;
+            }
+        }
+    }
+
+    group.into_iter().collect()
+}
+
+/// `property`'s unit depends on the encoder (`x264enc`'s `bitrate` is
+/// kbit/sec; the vpx/opus encoders are bit/sec), so convert on the way in.
+fn property_value(bitrate_bps: i32, in_kbit: bool) -> i32 {
+    if in_kbit {
+        bitrate_bps / 1000
+    } else {
+        bitrate_bps
+    }
+}
+
+/// Pushes `bitrate` bits/sec onto every shared video/audio encoder branch,
+/// splitting it between video and audio and clamping audio to its own sane
+/// range. Every codec branch for a media type gets the same target: each
+/// branch is its own m-line linked into every peer, and nothing here tracks
+/// which of those m-lines a peer's answer accepted, so there's no single
+/// "the" encoder to single out.
+fn apply_bitrate(
+    video_encoders: &[(gst::Element, Codec)],
+    audio_encoders: &[(gst::Element, Codec)],
+    bitrate: u32,
+) {
+    let audio_bitrate = ((f64::from(bitrate) * AUDIO_BITRATE_SHARE) as i32)
+        .max(AUDIO_MIN_BITRATE)
+        .min(AUDIO_MAX_BITRATE);
+    let video_bitrate = (bitrate as i64 - i64::from(audio_bitrate)).max(0) as i32;
+
+    for (encoder, codec) in video_encoders {
+        let value = property_value(video_bitrate, codec.bitrate_in_kbit);
+        if let Err(err) = encoder.set_property(codec.bitrate_property, &value) {
+            println!(
+                "Failed to set {} {}: {:?}",
+                codec.encoder_factory, codec.bitrate_property, err
+            );
+        }
+    }
+    for (encoder, codec) in audio_encoders {
+        let value = property_value(audio_bitrate, codec.bitrate_in_kbit);
+        if let Err(err) = encoder.set_property(codec.bitrate_property, &value) {
+            println!(
+                "Failed to set {} {}: {:?}",
+                codec.encoder_factory, codec.bitrate_property, err
+            );
+        }
+    }
+}
+
+/// Owns one [`BandwidthEstimator`] per peer and re-applies the most
+/// conservative (lowest) of their estimates to every shared encoder branch
+/// after every update, since all peers currently receive the same pre-tee
+/// encoded streams.
+pub struct EncoderBitrateController {
+    min_bitrate: u32,
+    max_bitrate: u32,
+    video_encoders: Vec<(gst::Element, Codec)>,
+    audio_encoders: Vec<(gst::Element, Codec)>,
+    estimators: Mutex<HashMap<String, BandwidthEstimator>>,
+    // One send-time table (keyed by TWCC sequence number) per codec branch,
+    // indexed the same way as `watch_outgoing_payloader`/`watch_peer_feedback`'s
+    // `stream_index`: each branch's payloader assigns its own TWCC sequence
+    // numbers starting from 0, so a table shared between branches would have
+    // their packets colliding on the same sequence number and clobbering
+    // each other's send time/size. Every peer linked to a branch shares its
+    // table read-only (see `decode_twcc_groups`): the payloader that writes
+    // it sits upstream of the per-peer tee, so there's exactly one send time
+    // per packet regardless of how many peers later report feedback on it.
+    send_times: Vec<Mutex<HashMap<u16, (f64, u32)>>>,
+    epoch: Instant,
+}
+
+impl EncoderBitrateController {
+    pub fn new(
+        video_encoders: Vec<(gst::Element, Codec)>,
+        audio_encoders: Vec<(gst::Element, Codec)>,
+        min_bitrate: u32,
+        max_bitrate: u32,
+    ) -> Arc<EncoderBitrateController> {
+        let stream_count = video_encoders.len() + audio_encoders.len();
+        Arc::new(EncoderBitrateController {
+            min_bitrate,
+            max_bitrate,
+            video_encoders,
+            audio_encoders,
+            estimators: Mutex::new(HashMap::new()),
+            send_times: (0..stream_count).map(|_| Mutex::new(HashMap::new())).collect(),
+            epoch: Instant::now(),
+        })
+    }
+
+    pub fn add_peer(&self, peer_id: &str) {
+        // Start conservatively and let steady-state growth (see
+        // `BandwidthEstimator::on_packet_group`) ramp up to `max_bitrate`,
+        // rather than starting pinned at the ceiling with nowhere to grow.
+        self.estimators
+            .lock()
+            .unwrap()
+            .insert(peer_id.to_string(), BandwidthEstimator::new(self.min_bitrate));
+    }
+
+    pub fn remove_peer(&self, peer_id: &str) {
+        self.estimators.lock().unwrap().remove(peer_id);
+    }
+
+    fn apply(&self, peer_id: &str, groups: Vec<PacketGroup>) {
+        if groups.is_empty() {
+            return;
+        }
+        let mut estimators = self.estimators.lock().unwrap();
+        let estimator = match estimators.get_mut(peer_id) {
+            Some(estimator) => estimator,
+            None => return,
+        };
+        for group in groups {
+            estimator.on_packet_group(group);
+        }
+        let min_estimate = estimators
+            .values()
+            .map(|estimator| estimator.current_bitrate)
+            .min()
+            .unwrap_or(self.max_bitrate)
+            .max(self.min_bitrate)
+            .min(self.max_bitrate);
+        drop(estimators);
+        apply_bitrate(&self.video_encoders, &self.audio_encoders, min_estimate);
+    }
+
+    /// Adds the TWCC RTP header extension to `payloader` and records the
+    /// local send time of every packet it emits, keyed by TWCC sequence
+    /// number, so later feedback can be matched back to a send time.
+    /// `stream_index` identifies which codec branch (and so which SDP
+    /// m-line) `payloader` belongs to; it must match the index later passed
+    /// to [`watch_peer_feedback`] for that same branch.
+    pub fn watch_outgoing_payloader(
+        self: &Arc<Self>,
+        payloader: &gst::Element,
+        ext_id: u8,
+        stream_index: usize,
+    ) {
+        let extension = gst_rtp::RTPHeaderExtension::create_from_uri(TWCC_EXTENSION_URI)
+            .expect("Failed to create TWCC RTP header extension");
+        extension.set_id(u32::from(ext_id));
+        payloader.emit("add-extension", &[&extension]).unwrap();
+
+        let controller = self.clone();
+        let pad = payloader.get_static_pad("src").unwrap();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+            if let Some(buffer) = info.get_buffer() {
+                if let Ok(rtp_buffer) = gst_rtp::RTPBuffer::from_buffer_readable(buffer) {
+                    if let Some(data) = rtp_buffer.get_extension_onebyte_header(ext_id, 0) {
+                        if data.len() >= 2 {
+                            let seq = (u16::from(data[0]) << 8) | u16::from(data[1]);
+                            let now = controller.epoch.elapsed();
+                            let now_secs =
+                                now.as_secs() as f64 + f64::from(now.subsec_nanos()) * 1e-9;
+                            controller.send_times[stream_index]
+                                .lock()
+                                .unwrap()
+                                .insert(seq, (now_secs, buffer.get_size() as u32 * 8));
+                        }
+                    }
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Connects to `webrtcbin`'s internal rtpbin session for `stream_index`
+    /// so TWCC feedback reports for `peer_id`'s stream feed this
+    /// controller's estimator. `stream_index` must match the rtpbin session
+    /// id that branch ends up on, which -- since sessions are numbered in
+    /// the order their m-lines were added -- means branches must be linked
+    /// into every peer's webrtcbin in the same order they were passed to
+    /// [`EncoderBitrateController::new`].
+    pub fn watch_peer_feedback(
+        self: &Arc<Self>,
+        webrtcbin: &gst::Element,
+        peer_id: &str,
+        stream_index: usize,
+    ) {
+        let rtpbin = webrtcbin
+            .clone()
+            .dynamic_cast::<gst::Bin>()
+            .unwrap()
+            .get_by_name("rtpbin")
+            .expect("webrtcbin has no internal rtpbin");
+        let session = rtpbin
+            .emit("get-internal-session", &[&(stream_index as u32)])
+            .unwrap()
+            .unwrap()
+            .get::<gst::Element>()
+            .expect("Invalid argument");
+
+        let controller = self.clone();
+        let peer_id = peer_id.to_string();
+        session
+            .connect("on-feedback-rtcp", false, move |values| {
+                let packet_type = values[1].get::<u32>().expect("Invalid argument");
+                let fb_type = values[2].get::<u32>().expect("Invalid argument");
+                let fci = values[5].get::<gst::Buffer>().expect("Invalid argument");
+                if packet_type == RTCP_RTPFB_TYPE && fb_type == TWCC_FMT {
+                    if let Ok(map) = fci.map_readable() {
+                        let groups = decode_twcc_groups(
+                            &map,
+                            &controller.send_times[stream_index].lock().unwrap(),
+                        );
+                        controller.apply(&peer_id, groups);
+                    }
+                }
+                None
+            })
+            .unwrap();
+    }
+}
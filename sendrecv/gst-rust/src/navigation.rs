@@ -0,0 +1,98 @@
+//! Translates JSON navigation events received over a `WebRTCDataChannel`
+//! into GStreamer `GstNavigation` events pushed upstream into the pipeline,
+//! so a remote peer can drive `videotestsrc` (or any navigation-aware
+//! source) the way `webrtcsink`'s data channel does.
+
+use failure::Error;
+use gst;
+use gst::prelude::*;
+use gst_video;
+use gst_webrtc;
+use serde_json;
+
+/// JSON schema for incoming navigation events, matching the one
+/// `webrtcsink`/`webrtcsrc` expose over their navigation data channel.
+#[derive(Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum NavigationEvent {
+    MouseMove { x: f64, y: f64 },
+    MouseButtonPress { button: i32, x: f64, y: f64 },
+    MouseButtonRelease { button: i32, x: f64, y: f64 },
+    KeyPress { key: String },
+    KeyRelease { key: String },
+    Wheel { delta_x: f64, delta_y: f64 },
+}
+
+/// Converts a deserialized [`NavigationEvent`] into the event type
+/// `gstreamer_video` builds real `GST_EVENT_NAVIGATION` events from, so
+/// navigation-aware elements (which dispatch on that event type, not on
+/// generic custom events) actually see it.
+fn to_gst_navigation_event(event: &NavigationEvent) -> gst_video::NavigationEvent {
+    match *event {
+        NavigationEvent::MouseMove { x, y } => gst_video::NavigationEvent::MouseMove { x, y },
+        NavigationEvent::MouseButtonPress { button, x, y } => {
+            gst_video::NavigationEvent::MouseButtonPress { button, x, y }
+        }
+        NavigationEvent::MouseButtonRelease { button, x, y } => {
+            gst_video::NavigationEvent::MouseButtonRelease { button, x, y }
+        }
+        NavigationEvent::KeyPress { ref key } => {
+            gst_video::NavigationEvent::KeyPress { key: key.clone() }
+        }
+        NavigationEvent::KeyRelease { ref key } => {
+            gst_video::NavigationEvent::KeyRelease { key: key.clone() }
+        }
+        NavigationEvent::Wheel { delta_x, delta_y } => gst_video::NavigationEvent::MouseScroll {
+            x: 0.0,
+            y: 0.0,
+            delta_x,
+            delta_y,
+        },
+    }
+}
+
+/// Parses a navigation data channel message and pushes the corresponding
+/// `GstNavigation` event upstream into `pipeline`. Malformed messages are
+/// logged and otherwise ignored; a misbehaving or hostile peer shouldn't be
+/// able to crash the pipeline.
+pub fn handle_message(pipeline: &gst::Pipeline, message: &str) {
+    let event = match serde_json::from_str::<NavigationEvent>(message) {
+        Ok(event) => event,
+        Err(err) => {
+            println!("Ignoring malformed navigation event {:?}: {:?}", message, err);
+            return;
+        }
+    };
+    let gst_event = to_gst_navigation_event(&event).build();
+    pipeline.send_event(gst_event);
+}
+
+/// Negotiates a `"input"` data channel on `webrtcbin` and wires incoming
+/// messages on it to [`handle_message`], so the remote peer can drive
+/// `pipeline`. Returns the channel so callers can also send messages back
+/// over it with [`send`].
+pub fn add_data_channel(
+    webrtcbin: &gst::Element,
+    pipeline: &gst::Pipeline,
+) -> Result<gst_webrtc::WebRTCDataChannel, Error> {
+    let channel = webrtcbin
+        .emit("create-data-channel", &[&"input", &None::<gst::Structure>])?
+        .ok_or_else(|| format_err!("webrtcbin did not return a data channel"))?
+        .get::<gst_webrtc::WebRTCDataChannel>()
+        .expect("Invalid argument");
+
+    let pipeline_clone = pipeline.clone();
+    channel.connect("on-message-string", false, move |values| {
+        let message = values[1].get::<String>().expect("Invalid argument");
+        handle_message(&pipeline_clone, &message);
+        None
+    })?;
+
+    Ok(channel)
+}
+
+/// Sends `message` to the remote peer over a channel set up by
+/// [`add_data_channel`].
+pub fn send(channel: &gst_webrtc::WebRTCDataChannel, message: &str) {
+    channel.emit("send-string", &[&message]).unwrap();
+}
@@ -0,0 +1,81 @@
+//! A pluggable signalling abstraction. `main` picks an implementation with
+//! `--signaller`: the bespoke HELLO/ROOM WebSocket protocol used by
+//! `WsClient`, or plain-HTTP WHIP (see `whip`).
+
+use failure::Error;
+use gst_webrtc;
+use ws;
+
+pub mod whip;
+
+/// Invoked with the remote peer's SDP (offer or answer) once a signalling
+/// backend has received one.
+pub type RemoteSdpHandler = Box<Fn(&str, gst_webrtc::WebRTCSessionDescription) + Send>;
+/// Invoked with a remote ICE candidate for `peer_id` once a signalling
+/// backend has received one.
+pub type RemoteIceHandler = Box<Fn(&str, u32, &str) + Send>;
+
+pub trait Signaller {
+    /// Announces this client to the signalling backend.
+    fn register(&mut self) -> Result<(), Error>;
+    /// Sends our local SDP (offer or answer) for `peer_id`.
+    fn send_sdp(&mut self, peer_id: &str, sdp: &gst_webrtc::WebRTCSessionDescription);
+    /// Sends a local ICE candidate for `peer_id`.
+    fn send_ice(&mut self, peer_id: &str, mline_index: u32, candidate: &str);
+    /// Registers the callback invoked when a remote SDP arrives.
+    fn on_remote_sdp(&mut self, handler: RemoteSdpHandler);
+    /// Registers the callback invoked when a remote ICE candidate arrives.
+    fn on_remote_ice(&mut self, handler: RemoteIceHandler);
+}
+
+/// Thin `Signaller` adapter over the bespoke HELLO/ROOM WebSocket protocol.
+/// Incoming SDP/ICE for this backend is handled inline by
+/// `WsClient::on_message`, which already has direct access to the room's
+/// peer map, so `on_remote_sdp`/`on_remote_ice` are unused here. `register`
+/// is a no-op as well: the `HELLO` registration is sent by
+/// `register_with_server` in `main.rs`.
+pub struct WebSocketSignaller {
+    sender: ws::Sender,
+}
+
+impl WebSocketSignaller {
+    pub fn new(sender: ws::Sender) -> WebSocketSignaller {
+        WebSocketSignaller { sender: sender }
+    }
+}
+
+impl Signaller for WebSocketSignaller {
+    fn register(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn send_sdp(&mut self, peer_id: &str, sdp: &gst_webrtc::WebRTCSessionDescription) {
+        let type_ = match sdp.get_type() {
+            gst_webrtc::WebRTCSDPType::Offer => "offer",
+            gst_webrtc::WebRTCSDPType::Answer => "answer",
+            other => panic!("Unexpected SDP type to send: {:?}", other),
+        };
+        let message = json!({
+            "peerId": peer_id,
+            "sdp": {
+                "type": type_,
+                "sdp": sdp.get_sdp().as_text().unwrap(),
+            }
+        });
+        self.sender.send(message.to_string()).unwrap();
+    }
+
+    fn send_ice(&mut self, peer_id: &str, mline_index: u32, candidate: &str) {
+        let message = json!({
+            "peerId": peer_id,
+            "ice": {
+                "candidate": candidate,
+                "sdpMLineIndex": mline_index,
+            }
+        });
+        self.sender.send(message.to_string()).unwrap();
+    }
+
+    fn on_remote_sdp(&mut self, _handler: RemoteSdpHandler) {}
+    fn on_remote_ice(&mut self, _handler: RemoteIceHandler) {}
+}
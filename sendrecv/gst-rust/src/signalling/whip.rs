@@ -0,0 +1,102 @@
+//! A `Signaller` that publishes to a standard WHIP (WebRTC-HTTP Ingestion
+//! Protocol) endpoint instead of the bespoke HELLO/ROOM relay: the local SDP
+//! offer is POSTed as `application/sdp`, the `201 Created` response body is
+//! the answer and its `Location` header is the session resource to DELETE
+//! on teardown.
+
+use failure::Error;
+use gst_sdp;
+use gst_webrtc;
+use reqwest;
+use std::sync::Mutex;
+
+use signalling::{RemoteIceHandler, RemoteSdpHandler, Signaller};
+
+pub struct WhipSignaller {
+    endpoint: String,
+    client: reqwest::Client,
+    resource_url: Mutex<Option<String>>,
+    on_remote_sdp: Mutex<Option<RemoteSdpHandler>>,
+    on_remote_ice: Mutex<Option<RemoteIceHandler>>,
+}
+
+impl WhipSignaller {
+    pub fn new(endpoint: &str) -> WhipSignaller {
+        WhipSignaller {
+            endpoint: endpoint.to_string(),
+            client: reqwest::Client::new(),
+            resource_url: Mutex::new(None),
+            on_remote_sdp: Mutex::new(None),
+            on_remote_ice: Mutex::new(None),
+        }
+    }
+
+    /// DELETEs the session resource handed back in the POST response's
+    /// `Location` header, if we ever got one.
+    pub fn teardown(&self) {
+        if let Some(resource_url) = self.resource_url.lock().unwrap().take() {
+            if let Err(err) = self.client.delete(&resource_url).send() {
+                println!(
+                    "Failed to DELETE WHIP resource {}: {:?}",
+                    resource_url, err
+                );
+            }
+        }
+    }
+}
+
+impl Signaller for WhipSignaller {
+    fn register(&mut self) -> Result<(), Error> {
+        // WHIP has no separate registration step: the POST in send_sdp both
+        // creates and negotiates the session.
+        Ok(())
+    }
+
+    fn send_sdp(&mut self, peer_id: &str, sdp: &gst_webrtc::WebRTCSessionDescription) {
+        let offer_sdp = sdp.get_sdp().as_text().unwrap();
+        let mut response = self.client
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/sdp")
+            .body(offer_sdp)
+            .send()
+            .expect("Failed to POST WHIP offer");
+
+        if response.status() != reqwest::StatusCode::CREATED {
+            panic!(
+                "WHIP endpoint returned unexpected status {}",
+                response.status()
+            );
+        }
+
+        let resource_url = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .expect("WHIP response missing Location header")
+            .to_str()
+            .unwrap()
+            .to_string();
+        *self.resource_url.lock().unwrap() = Some(resource_url);
+
+        let answer_sdp = response.text().expect("Failed to read WHIP answer body");
+        let parsed = gst_sdp::SDPMessage::parse_buffer(answer_sdp.as_bytes()).unwrap();
+        let answer =
+            gst_webrtc::WebRTCSessionDescription::new(gst_webrtc::WebRTCSDPType::Answer, parsed);
+
+        if let Some(handler) = self.on_remote_sdp.lock().unwrap().as_ref() {
+            handler(peer_id, answer);
+        }
+    }
+
+    fn send_ice(&mut self, _peer_id: &str, _mline_index: u32, _candidate: &str) {
+        // WHIP negotiates ICE candidates inline in the SDP (non-trickle);
+        // there is no out-of-band path to send them over.
+    }
+
+    fn on_remote_sdp(&mut self, handler: RemoteSdpHandler) {
+        *self.on_remote_sdp.lock().unwrap() = Some(handler);
+    }
+
+    fn on_remote_ice(&mut self, handler: RemoteIceHandler) {
+        *self.on_remote_ice.lock().unwrap() = Some(handler);
+    }
+}